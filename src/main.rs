@@ -1,47 +1,216 @@
-extern crate num;
+extern crate clap;
 extern crate image;
+extern crate indicatif;
+extern crate num;
+extern crate rand;
 extern crate rayon;
 
 use std::str::FromStr;
-use num::Complex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use clap::{App, Arg};
 use image::ColorType;
+use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
+use num::Complex;
+use rand::Rng;
 use rayon::prelude::*;
 
 fn main() {
-    // get command line arguments
-    let args: Vec<String> = std::env::args().collect();
-
-    // check that there are enough
-    if args.len() != 5 {
-        eprintln!("Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT");
-        eprintln!(
-            "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-            args[0]
-        );
-        std::process::exit(0);
+    let matches = App::new("mandelbrot")
+        .about("Renders escape-time fractals to an image file")
+        .arg(
+            Arg::with_name("FILE")
+                .help("output image path; encoding is inferred from the extension")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("dimensions")
+                .long("dimensions")
+                .value_name("WIDTHxHEIGHT")
+                .default_value("1000x750"),
+        )
+        .arg(
+            Arg::with_name("upper-left")
+                .long("upper-left")
+                .value_name("RE,IM")
+                .default_value("-1.20,0.35"),
+        )
+        .arg(
+            Arg::with_name("lower-right")
+                .long("lower-right")
+                .value_name("RE,IM")
+                .default_value("-1,0.20"),
+        )
+        .arg(
+            Arg::with_name("iterations")
+                .long("iterations")
+                .alias("limit")
+                .value_name("N")
+                .default_value("255"),
+        )
+        .arg(
+            Arg::with_name("fractal")
+                .long("fractal")
+                .value_name("KIND")
+                .possible_values(&["mandelbrot", "multibrot3", "burningship"])
+                .default_value("mandelbrot")
+                .help("the fractal family to iterate; orthogonal to --mode"),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("MODE")
+                .possible_values(&["escape-time", "buddhabrot", "color"])
+                .default_value("escape-time")
+                .help("the rendering algorithm to use"),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("NAME")
+                .possible_values(&["grayscale", "hsv"])
+                .default_value("hsv")
+                .help("only used when --mode=color"),
+        )
+        .arg(
+            Arg::with_name("smooth")
+                .long("smooth")
+                .help("use the continuous escape count instead of the raw iteration count; only used when --mode=escape-time"),
+        )
+        .arg(
+            Arg::with_name("samples")
+                .long("samples")
+                .value_name("N")
+                .default_value("1000000")
+                .help("number of orbits to sample; only used when --mode=buddhabrot"),
+        )
+        .get_matches();
+
+    let filename = matches.value_of("FILE").unwrap();
+    let bounds =
+        parse_pair(matches.value_of("dimensions").unwrap(), 'x').expect("error parsing image dimensions");
+    let upper_left =
+        parse_complex(matches.value_of("upper-left").unwrap()).expect("error parsing upper left corner point");
+    let lower_right =
+        parse_complex(matches.value_of("lower-right").unwrap()).expect("error parsing lower right corner point");
+    let limit: u32 = matches
+        .value_of("iterations")
+        .unwrap()
+        .parse()
+        .expect("error parsing iteration limit");
+    let kind = matches
+        .value_of("fractal")
+        .unwrap()
+        .parse()
+        .expect("error parsing fractal kind");
+    let smooth = matches.is_present("smooth");
+
+    match matches.value_of("mode").unwrap() {
+        "buddhabrot" => {
+            let samples: usize = matches
+                .value_of("samples")
+                .unwrap()
+                .parse()
+                .expect("error parsing sample count");
+
+            let mut img = Image::new(bounds.0, bounds.1);
+            img.render_buddhabrot(upper_left, lower_right, kind, limit, samples);
+            img.to_file(filename).expect("error writing image file");
+        }
+        "color" => {
+            let palette = matches.value_of("palette").unwrap().parse().expect("error parsing palette");
+
+            let mut img = ColorImage::new(bounds.0, bounds.1);
+            img.render(upper_left, lower_right, kind, limit, palette);
+            img.to_file(filename).expect("error writing image file");
+        }
+        _ => {
+            let mut img = Image::new(bounds.0, bounds.1);
+            img.render(upper_left, lower_right, kind, smooth, limit);
+            img.to_file(filename).expect("error writing image file");
+        }
     }
+}
 
-    // parse the arguments
-    let bounds = parse_pair(&args[2], 'x').expect("error parsing image dimensions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left corner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right corner point");
+/// Build a progress bar sized to `len` units of work, shown only while the
+/// render is in flight.
+fn render_progress_bar(len: u64) -> ProgressBar {
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:40} {pos}/{len} ({eta})")
+            .unwrap(),
+    );
+    bar
+}
+
+/// The family of escape-time fractal to render, selected by the single
+/// iteration step applied to `z` each pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FractalKind {
+    Mandelbrot,
+    Multibrot3,
+    BurningShip,
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mandelbrot" => Ok(FractalKind::Mandelbrot),
+            "multibrot3" => Ok(FractalKind::Multibrot3),
+            "burningship" | "burning-ship" => Ok(FractalKind::BurningShip),
+            _ => Err(format!("unrecognized fractal kind: {}", s)),
+        }
+    }
+}
+
+/// Apply a single iteration step of the given fractal family.
+fn fractal_step(z: Cplx64, c: Cplx64, kind: FractalKind) -> Cplx64 {
+    match kind {
+        FractalKind::Mandelbrot => z * z + c,
+        FractalKind::Multibrot3 => z * z * z + c,
+        FractalKind::BurningShip => {
+            let z = Complex {
+                re: z.re.abs(),
+                im: z.im.abs(),
+            };
+            z * z + c
+        }
+    }
+}
 
-    // make image struct
-    let mut img = Image::new(bounds.0, bounds.1);
+/// A test for `FractalKind`'s `FromStr` impl
+#[test]
+fn test_fractal_kind_from_str() {
+    assert_eq!("mandelbrot".parse(), Ok(FractalKind::Mandelbrot));
+    assert_eq!("multibrot3".parse(), Ok(FractalKind::Multibrot3));
+    assert_eq!("burningship".parse(), Ok(FractalKind::BurningShip));
+    assert_eq!("burning-ship".parse(), Ok(FractalKind::BurningShip));
+    assert!("nonsense".parse::<FractalKind>().is_err());
+}
 
-    // render image
-    img.render(upper_left, lower_right);
+/// A test for `fractal_step`
+#[test]
+fn test_fractal_step() {
+    let z = Complex { re: 1.0, im: -2.0 };
+    let c = Complex { re: 0.5, im: 0.5 };
 
-    // write the results to file
-    img.to_file(&args[1]).expect("error writing PNG file");
+    assert_eq!(fractal_step(z, c, FractalKind::Mandelbrot), z * z + c);
+    assert_eq!(fractal_step(z, c, FractalKind::Multibrot3), z * z * z + c);
+    assert_eq!(
+        fractal_step(z, c, FractalKind::BurningShip),
+        Complex { re: 1.0, im: 2.0 } * Complex { re: 1.0, im: 2.0 } + c
+    );
 }
 
 trait Mandelbrot {
-    fn escape_time(self, limit: u32) -> Option<u32>;
+    fn escape_time(self, limit: u32, kind: FractalKind) -> Option<(u32, Cplx64)>;
 }
 
 trait ToColor<ColorOut>: Mandelbrot {
-    fn escape_color(self) -> ColorOut;
+    fn escape_color(self, kind: FractalKind, limit: u32) -> ColorOut;
+    fn escape_color_smooth(self, kind: FractalKind, limit: u32) -> ColorOut;
 }
 
 /// Shorthand for long type name
@@ -50,20 +219,20 @@ type Cplx64 = Complex<f64>;
 
 impl Mandelbrot for Cplx64 {
     /// Calculate how many iterations a complex number can withstand before
-    /// flying out to infinity
-    fn escape_time(self, limit: u32) -> Option<u32> {
+    /// flying out to infinity, along with the value of `z` at the moment it
+    /// escaped (used for smooth coloring)
+    fn escape_time(self, limit: u32, kind: FractalKind) -> Option<(u32, Cplx64)> {
         // initial condition: zero
         let mut z = Complex { re: 0.0, im: 0.0 };
 
         // iterate on this value until its magnitude exceeds 4.0
         // (or up to the limit)
         for i in 0..limit {
-            z *= z;
-            z += self;
+            z = fractal_step(z, self, kind);
 
             // report that the number has escaped
             if z.norm_sqr() > 4.0 {
-                return Some(i);
+                return Some((i, z));
             }
         }
 
@@ -72,6 +241,49 @@ impl Mandelbrot for Cplx64 {
     }
 }
 
+/// Number of extra iterations to run past the escape test so that `|z|` is
+/// comfortably above 2 and the double-log in `mu` below is well-conditioned
+const SMOOTHING_STEPS: u32 = 4;
+
+/// Fixed (upper-left, lower-right) region that `render_buddhabrot` draws its
+/// `c` samples from, independent of whatever viewport is being rendered.
+/// Every point that can possibly escape lives within `|c| <= 2`, so this is
+/// the classic bounding box for the technique.
+const BUDDHABROT_SAMPLE_REGION: (Cplx64, Cplx64) = (
+    Complex { re: -2.0, im: 2.0 },
+    Complex { re: 2.0, im: -2.0 },
+);
+
+/// Compute the normalized (fractional) iteration count for a point, or
+/// `None` if the point never escapes within `limit` iterations.
+fn escape_mu(c: Cplx64, limit: u32, kind: FractalKind) -> Option<f64> {
+    let (count, mut z) = c.escape_time(limit, kind)?;
+
+    let mut n = count;
+    for _ in 0..SMOOTHING_STEPS {
+        z = fractal_step(z, c, kind);
+        n += 1;
+    }
+
+    Some(n as f64 + 1.0 - (0.5 * z.norm_sqr().ln()).ln() / 2f64.ln())
+}
+
+/// A test for `escape_mu`
+#[test]
+fn test_escape_mu() {
+    // well inside the set: never escapes
+    assert_eq!(
+        escape_mu(Complex { re: 0.0, im: 0.0 }, 255, FractalKind::Mandelbrot),
+        None
+    );
+
+    // escapes after a couple of iterations, and the fractional count
+    // should land close to (but not exactly on) the integer count
+    let mu = escape_mu(Complex { re: 1.0, im: 0.0 }, 255, FractalKind::Mandelbrot)
+        .expect("this point should escape");
+    assert!((mu - 2.2956431687820036).abs() < 1e-9);
+}
+
 impl<P> ToColor<P> for Cplx64
 where
     P: Default
@@ -80,15 +292,29 @@ where
         + num::ToPrimitive
         + num::Bounded,
 {
-    /// Take a point and determine its display color
-    fn escape_color(self) -> P {
-        let limit = P::max_value().to_u32().unwrap();
-        if let Some(count) = self.escape_time(limit) {
-            P::max_value() - P::from_u32(count).unwrap()
+    /// Take a point and determine its display color from its raw (integer)
+    /// escape count, scaled against `limit`
+    fn escape_color(self, kind: FractalKind, limit: u32) -> P {
+        if let Some((count, _)) = self.escape_time(limit, kind) {
+            let scaled = count as f64 / limit as f64 * P::max_value().to_f64().unwrap();
+            P::max_value() - P::from_f64(scaled).unwrap()
         } else {
             P::default()
         }
     }
+
+    /// Take a point and determine its display color from its smooth,
+    /// fractional escape count, removing the banding `escape_color` shows
+    /// at integer boundaries
+    fn escape_color_smooth(self, kind: FractalKind, limit: u32) -> P {
+        match escape_mu(self, limit, kind) {
+            Some(mu) => {
+                let scaled = (mu / limit as f64).clamp(0.0, 1.0) * P::max_value().to_f64().unwrap();
+                P::max_value() - P::from_f64(scaled).unwrap_or_else(P::zero)
+            }
+            None => P::default(),
+        }
+    }
 }
 
 /// Type representing a 2D image
@@ -118,31 +344,20 @@ where
         }
     }
 
-    /// Turn an image into a series of index pairs
-    fn get_indices(&self) -> Vec<(usize, usize)> {
-        /* This 2D array is in row-major order.
-         * Row indices are in blocks, and increment once per row.
-         * Column indices repeatedly increase from (0) to (width-1).
-         */
-
-        // for each row
-        (0..self.height)
-            .flat_map(|elem| {
-                // repeat row index infinitely
-                std::iter::repeat(elem)
-                    // stop at the width
-                    .take(self.width)
-                    // pair it off with column indices
-                    .enumerate()
-            })
-            // collect vector of tuples
-            .collect()
-    }
-
-    /// Populate your pixels with the appropriate escape values
-    fn render(&mut self, upper_left: Cplx64, lower_right: Cplx64) {
+    /// Populate your pixels with the appropriate escape values, iterating
+    /// each point up to `limit` times. When `smooth` is set, uses the
+    /// fractional escape count instead of the raw iteration count, which
+    /// removes banding at escape-count boundaries.
+    fn render(
+        &mut self,
+        upper_left: Cplx64,
+        lower_right: Cplx64,
+        kind: FractalKind,
+        smooth: bool,
+        limit: u32,
+    ) {
         // get column/row indices for each point in the map
-        let indices = self.get_indices();
+        let indices = get_indices(self.width, self.height);
 
         // get complex coordinates from each index pair
         let points: Vec<Cplx64> = indices
@@ -153,25 +368,361 @@ where
             .collect();
 
         // get the appropriate colors from the complex values
+        let bar = render_progress_bar(points.len() as u64);
         self.pixels = points
             .into_par_iter()
-            .map(|point| point.escape_color())
+            .progress_with(bar)
+            .map(|point| {
+                if smooth {
+                    point.escape_color_smooth(kind, limit)
+                } else {
+                    point.escape_color(kind, limit)
+                }
+            })
+            .collect();
+    }
+
+    /// Populate your pixels via Buddhabrot accumulation instead of the
+    /// per-pixel escape-time map.
+    ///
+    /// Samples `c` uniformly across [`BUDDHABROT_SAMPLE_REGION`], walks the
+    /// `kind` orbit, and for every orbit that escapes, records a hit for
+    /// each intermediate `z` that lands inside the viewport. Pixels with
+    /// more hits light up brighter once the counts are normalized against
+    /// the brightest pixel. Sampling from a region much larger than the
+    /// viewport (rather than the viewport itself) is what makes this a
+    /// Buddhabrot instead of a re-derivation of the viewport's own
+    /// escape-time density: the trajectories that light up a zoomed-in
+    /// viewport overwhelmingly originate from `c` values outside it.
+    fn render_buddhabrot(
+        &mut self,
+        upper_left: Cplx64,
+        lower_right: Cplx64,
+        kind: FractalKind,
+        limit: u32,
+        samples: usize,
+    ) {
+        // hit counts, shared across rayon workers
+        let counts: Vec<AtomicU32> = (0..self.width * self.height)
+            .map(|_| AtomicU32::new(0))
+            .collect();
+
+        let bar = render_progress_bar(samples as u64);
+        (0..samples).into_par_iter().progress_with(bar).for_each(|_| {
+            let mut rng = rand::thread_rng();
+
+            // sample a point uniformly across the full sample region, not
+            // just the viewport being rendered
+            let (region_upper_left, region_lower_right) = BUDDHABROT_SAMPLE_REGION;
+            let c = Complex {
+                re: rng.gen_range(region_upper_left.re, region_lower_right.re),
+                im: rng.gen_range(region_lower_right.im, region_upper_left.im),
+            };
+
+            // walk the orbit, recording every point along the way
+            let mut trajectory = Vec::with_capacity(limit as usize);
+            let mut z = Complex { re: 0.0, im: 0.0 };
+            let mut escaped = false;
+            for _ in 0..limit {
+                z = fractal_step(z, c, kind);
+                trajectory.push(z);
+
+                if z.norm_sqr() > 4.0 {
+                    escaped = true;
+                    break;
+                }
+            }
+
+            // non-escaping orbits contribute nothing
+            if !escaped {
+                return;
+            }
+
+            for point in trajectory {
+                if let Some((col, row)) =
+                    point_to_pixel((self.width, self.height), point, upper_left, lower_right)
+                {
+                    counts[row * self.width + col].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        // normalize the hottest pixel down to the output range
+        let max = counts.iter().map(|c| c.load(Ordering::Relaxed)).max().unwrap_or(0);
+
+        self.pixels = counts
+            .into_iter()
+            .map(|c| {
+                if max == 0 {
+                    P::default()
+                } else {
+                    let scaled = c.load(Ordering::Relaxed) as f64 / max as f64
+                        * P::max_value().to_f64().unwrap();
+                    P::from_f64(scaled).unwrap()
+                }
+            })
             .collect();
     }
 }
 
 impl Image<u8> {
-    /// Write the pixel array to a PNG file as 8-bit grayscale
+    /// Write the pixel array to a file, inferring the encoding from
+    /// `filename`'s extension (falling back to PNG when it's unrecognized)
+    fn to_file(&self, filename: &str) -> Result<(), std::io::Error> {
+        match file_extension(filename).as_deref() {
+            Some("ppm") | Some("pgm") => {
+                write_pnm(filename, self.width, self.height, &self.pixels, false)
+            }
+            Some("png") | Some("jpg") | Some("jpeg") => image::save_buffer(
+                filename,
+                &self.pixels,
+                self.width as u32,
+                self.height as u32,
+                ColorType::Gray(8),
+            ),
+            _ => save_as_png(
+                filename,
+                &self.pixels,
+                self.width as u32,
+                self.height as u32,
+                ColorType::Gray(8),
+            ),
+        }
+    }
+}
+
+/// Pull the lowercased extension off a filename, if it has one
+fn file_extension(filename: &str) -> Option<String> {
+    std::path::Path::new(filename)
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .map(str::to_lowercase)
+}
+
+/// Encode straight to PNG regardless of `filename`'s extension. Used as the
+/// fallback for unrecognized extensions, since `image::save_buffer` derives
+/// its format from the extension and errors out on anything it doesn't
+/// recognize rather than defaulting to PNG.
+fn save_as_png(
+    filename: &str,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    color: ColorType,
+) -> Result<(), std::io::Error> {
+    let file = std::fs::File::create(filename)?;
+    image::png::PNGEncoder::new(std::io::BufWriter::new(file)).encode(pixels, width, height, color)
+}
+
+/// A test for `file_extension`
+#[test]
+fn test_file_extension() {
+    assert_eq!(file_extension("mandel.png"), Some("png".to_string()));
+    assert_eq!(file_extension("mandel.PPM"), Some("ppm".to_string()));
+    assert_eq!(file_extension("mandel"), None);
+}
+
+/// Hand-rolled binary PNM writer: P5 for grayscale, P6 for RGB. Trivial to
+/// emit and a common target for fractal-rendering tools that don't want to
+/// pull in a full image-encoding dependency.
+fn write_pnm(
+    filename: &str,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    rgb: bool,
+) -> Result<(), std::io::Error> {
+    use std::io::Write;
+
+    let mut file = std::fs::File::create(filename)?;
+
+    writeln!(file, "{}", if rgb { "P6" } else { "P5" })?;
+    writeln!(file, "{} {}", width, height)?;
+    writeln!(file, "255")?;
+    file.write_all(pixels)?;
+
+    Ok(())
+}
+
+/// A test for `write_pnm`
+#[test]
+fn test_write_pnm() {
+    let dir = std::env::temp_dir();
+    let path = dir.join("mandelbrot_test_write_pnm.pgm");
+    let filename = path.to_str().unwrap();
+
+    write_pnm(filename, 2, 1, &[0, 255], false).unwrap();
+
+    let contents = std::fs::read(filename).unwrap();
+    assert!(contents.starts_with(b"P5\n2 1\n255\n"));
+    assert!(contents.ends_with(&[0, 255]));
+
+    std::fs::remove_file(filename).unwrap();
+}
+
+/// A color scheme for mapping (possibly fractional) escape counts to RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Palette {
+    /// Repeats the grayscale value across all three channels
+    Grayscale,
+    /// Sweeps hue around the color wheel as the escape count increases
+    Hsv,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "grayscale" | "gray" => Ok(Palette::Grayscale),
+            "hsv" => Ok(Palette::Hsv),
+            _ => Err(format!("unrecognized palette: {}", s)),
+        }
+    }
+}
+
+/// A test for `Palette::from_str`
+#[test]
+fn test_palette_from_str() {
+    assert_eq!("grayscale".parse(), Ok(Palette::Grayscale));
+    assert_eq!("gray".parse(), Ok(Palette::Grayscale));
+    assert_eq!("HSV".parse(), Ok(Palette::Hsv));
+    assert!("rainbow".parse::<Palette>().is_err());
+}
+
+impl Palette {
+    /// Map a smooth escape count (and the limit it was computed against)
+    /// into an RGB triple
+    fn color(self, mu: f64, limit: u32) -> (u8, u8, u8) {
+        let fraction = (mu / limit as f64).clamp(0.0, 1.0);
+
+        match self {
+            Palette::Grayscale => {
+                let v = (fraction * 255.0) as u8;
+                (v, v, v)
+            }
+            Palette::Hsv => hsv_to_rgb(fraction * 360.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// A test for `Palette::color`
+#[test]
+fn test_palette_color() {
+    assert_eq!(Palette::Grayscale.color(0.0, 100), (0, 0, 0));
+    assert_eq!(Palette::Grayscale.color(100.0, 100), (255, 255, 255));
+    assert_eq!(Palette::Grayscale.color(200.0, 100), (255, 255, 255));
+}
+
+/// Convert an HSV color (hue in degrees, saturation/value in `0.0..=1.0`)
+/// into 8-bit RGB
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> (u8, u8, u8) {
+    let c = value * saturation;
+    let h = hue / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    (
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+    )
+}
+
+/// A test for `hsv_to_rgb`
+#[test]
+fn test_hsv_to_rgb() {
+    assert_eq!(hsv_to_rgb(0.0, 0.0, 0.0), (0, 0, 0));
+    assert_eq!(hsv_to_rgb(0.0, 0.0, 1.0), (255, 255, 255));
+    assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), (255, 0, 0));
+    assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), (0, 255, 0));
+    assert_eq!(hsv_to_rgb(240.0, 1.0, 1.0), (0, 0, 255));
+}
+
+/// An image backed by a 3-byte-per-pixel (RGB) buffer, populated by mapping
+/// escape counts through a `Palette` instead of straight to grayscale
+struct ColorImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl ColorImage {
+    /// Make a blank (black) color image
+    fn new(width: usize, height: usize) -> Self {
+        ColorImage {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+        }
+    }
+
+    /// Populate your pixels via the smooth escape count, mapped through
+    /// `palette`. Interior points (which never escape) render black.
+    fn render(
+        &mut self,
+        upper_left: Cplx64,
+        lower_right: Cplx64,
+        kind: FractalKind,
+        limit: u32,
+        palette: Palette,
+    ) {
+        let indices = get_indices(self.width, self.height);
+
+        let points: Vec<Cplx64> = indices
+            .into_par_iter()
+            .map(|coords| {
+                pixel_to_point((self.width, self.height), coords, upper_left, lower_right)
+            })
+            .collect();
+
+        let bar = render_progress_bar(points.len() as u64);
+        let colors: Vec<(u8, u8, u8)> = points
+            .into_par_iter()
+            .progress_with(bar)
+            .map(|point| match escape_mu(point, limit, kind) {
+                Some(mu) => palette.color(mu, limit),
+                None => (0, 0, 0),
+            })
+            .collect();
+
+        self.pixels = colors
+            .into_iter()
+            .flat_map(|(r, g, b)| vec![r, g, b])
+            .collect();
+    }
+
+    /// Write the pixel array to a file, inferring the encoding from
+    /// `filename`'s extension (falling back to PNG when it's unrecognized)
     fn to_file(&self, filename: &str) -> Result<(), std::io::Error> {
-        image::save_buffer(
-            filename,
-            &self.pixels,
-            self.width as u32,
-            self.height as u32,
-            ColorType::Gray(8),
-        )?;
-
-        Ok(())
+        match file_extension(filename).as_deref() {
+            Some("ppm") | Some("pgm") => {
+                write_pnm(filename, self.width, self.height, &self.pixels, true)
+            }
+            Some("png") | Some("jpg") | Some("jpeg") => image::save_buffer(
+                filename,
+                &self.pixels,
+                self.width as u32,
+                self.height as u32,
+                ColorType::RGB(8),
+            ),
+            _ => save_as_png(
+                filename,
+                &self.pixels,
+                self.width as u32,
+                self.height as u32,
+                ColorType::RGB(8),
+            ),
+        }
     }
 }
 
@@ -229,6 +780,23 @@ fn test_parse_complex() {
     assert_eq!(parse_complex(",-0.0625"), None);
 }
 
+/// Turn an image's dimensions into a series of (column, row) index pairs
+fn get_indices(width: usize, height: usize) -> Vec<(usize, usize)> {
+    /* This 2D array is in row-major order.
+     * Row indices are in blocks, and increment once per row.
+     * Column indices repeatedly increase from (0) to (width-1).
+     */
+
+    // for each row
+    (0..height)
+        .flat_map(|elem| {
+            // repeat row index `width` times, paired off with column indices
+            std::iter::repeat_n(elem, width).enumerate()
+        })
+        // collect vector of tuples
+        .collect()
+}
+
 /// Translate pixel locations to complex coordinates
 fn pixel_to_point(
     bounds: (usize, usize),
@@ -262,3 +830,51 @@ fn test_pixel_to_point() {
         Complex { re: -0.5, im: -0.5 }
     );
 }
+
+/// Inverse of `pixel_to_point`: map a complex point back to the pixel that
+/// contains it, or `None` if it falls outside the viewport.
+fn point_to_pixel(
+    bounds: (usize, usize),
+    point: Cplx64,
+    upper_left: Cplx64,
+    lower_right: Cplx64,
+) -> Option<(usize, usize)> {
+    // figure out the bounding dimensions in complex space
+    let (width, height) = (
+        lower_right.re - upper_left.re,
+        upper_left.im - lower_right.im,
+    );
+
+    let col = (point.re - upper_left.re) / width * bounds.0 as f64;
+    let row = (upper_left.im - point.im) / height * bounds.1 as f64;
+
+    if col < 0.0 || row < 0.0 || col >= bounds.0 as f64 || row >= bounds.1 as f64 {
+        None
+    } else {
+        Some((col as usize, row as usize))
+    }
+}
+
+/// A test for `point_to_pixel`
+#[test]
+fn test_point_to_pixel() {
+    assert_eq!(
+        point_to_pixel(
+            (100, 100),
+            Complex { re: -0.5, im: -0.5 },
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 1.0, im: -1.0 },
+        ),
+        Some((25, 75))
+    );
+
+    assert_eq!(
+        point_to_pixel(
+            (100, 100),
+            Complex { re: 5.0, im: 5.0 },
+            Complex { re: -1.0, im: 1.0 },
+            Complex { re: 1.0, im: -1.0 },
+        ),
+        None
+    );
+}